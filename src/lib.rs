@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use std::collections::{HashMap, HashSet};
+
+use syn::{Expr, Ident, Ty};
+
+mod parse;
+
+pub use parse::ParseError;
+
+/// A set of resource/task names, e.g. the `resources: [A, B]` list.
+pub type Idents = HashSet<Ident>;
+
+/// `$ident: $ty = $expr` declarations, keyed by `$ident`.
+pub type Statics = HashMap<Ident, Static>;
+
+/// Task declarations, keyed by the task's name.
+pub type Tasks = HashMap<Ident, Task>;
+
+/// The parsed contents of an `app! { .. }` invocation.
+#[derive(Debug)]
+pub struct App {
+    pub device: quote::Tokens,
+    pub idle: Idle,
+    pub init: Init,
+    pub resources: Statics,
+    pub tasks: Tasks,
+}
+
+/// The `idle` field of `app!`.
+#[derive(Debug)]
+pub struct Idle {
+    pub locals: Statics,
+    pub path: quote::Tokens,
+    pub resources: Idents,
+}
+
+/// The `init` field of `app!`.
+#[derive(Debug)]
+pub struct Init {
+    pub path: quote::Tokens,
+}
+
+/// A single `resources` / `locals` declaration: `$ident: $ty = $expr`.
+#[derive(Debug)]
+pub struct Static {
+    pub ty: Ty,
+    pub expr: Expr,
+}
+
+/// A single entry in `tasks`.
+#[derive(Debug)]
+pub struct Task {
+    pub enabled: Option<bool>,
+    pub priority: Option<u8>,
+    pub resources: Idents,
+}
+
+/// Parses an `app! { .. }` invocation.
+pub fn app(input: &str) -> ::std::result::Result<App, Vec<ParseError>> {
+    parse::app(input)
+}
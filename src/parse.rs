@@ -1,405 +1,874 @@
 use std::collections::{HashMap, HashSet};
-use std::iter::Peekable;
-use std::slice::Iter;
+use std::fmt;
 
 use quote::Tokens;
 use syn::{self, DelimToken, Ident, IntTy, Lit, Token, TokenTree};
 
-use error::*;
-
 use {App, Idents, Idle, Init, Static, Statics, Task, Tasks};
 
-pub fn app(input: &str) -> Result<App> {
-    let tts = syn::parse_token_trees(input)?;
+/// Identifies *which* token tree a [`ParseError`] is about.
+///
+/// The `syn` version this crate is pinned to (0.11, pre-`proc-macro2`)
+/// never attaches a source span to an individual `Token`/`TokenTree` —
+/// there is no `syn::Span` to thread through, and `Ident` is just a
+/// wrapped `String`. `Location` is the closest stand-in available: it
+/// hangs on to the actual offending token tree (or records that parsing
+/// ran out of input) so error messages can still say exactly what was
+/// found, even though there's no line/column to point a `compile_error!`
+/// at.
+#[derive(Clone, Debug)]
+pub enum Location {
+    /// The offending token tree.
+    At(TokenTree),
+    /// Parsing ran out of input before finding what it needed, or the input
+    /// could not be split into token trees at all.
+    EndOfInput,
+}
+
+/// A structured parse error.
+///
+/// Unlike the stringly-typed `bail!`s this module used to produce, every
+/// variant here except `MissingField` (which has no token to point at)
+/// carries a [`Location`] naming the offending token, rather than leaving
+/// only a `Debug`-formatted message behind.
+#[derive(Clone, Debug)]
+pub enum ParseError {
+    /// Found a token that doesn't belong where it appeared.
+    UnexpectedToken { at: Location, found: String },
+    /// Expected some piece of syntax but found something else, or ran out
+    /// of tokens before finding it.
+    Expected { at: Location, what: String },
+    /// A field that may only be declared once was declared a second time.
+    DuplicateField { at: Location, name: String },
+    /// An entry in a list (an identifier in `resources: [..]`, a task in
+    /// `tasks { .. }`, ...) was listed more than once.
+    DuplicateItem {
+        at: Location,
+        kind: &'static str,
+        name: String,
+    },
+    /// A required field was never declared.
+    MissingField { name: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedToken { ref found, .. } => {
+                write!(f, "unexpected token: {}", found)
+            }
+            ParseError::Expected { ref what, .. } => {
+                write!(f, "expected {}", what)
+            }
+            ParseError::DuplicateField { ref name, .. } => {
+                write!(f, "duplicated `{}` field", name)
+            }
+            ParseError::DuplicateItem {
+                ref kind,
+                ref name,
+                ..
+            } => write!(f, "{} `{}` listed more than once", kind, name),
+            ParseError::MissingField { ref name } => {
+                write!(f, "`{}` field is missing", name)
+            }
+        }
+    }
+}
+
+impl ::std::error::Error for ParseError {
+    fn description(&self) -> &str {
+        "error parsing `app!` invocation"
+    }
+}
+
+type PResult<T> = ::std::result::Result<T, ParseError>;
+
+/// Returns the [`Location`] of `tt`, or [`Location::EndOfInput`] if there's
+/// no token left to point at.
+fn tt_location(tt: Option<&TokenTree>) -> Location {
+    match tt {
+        Some(tt) => Location::At(tt.clone()),
+        None => Location::EndOfInput,
+    }
+}
+
+/// Returns the [`Location`] of `ident`, wrapping it back into the
+/// `TokenTree` it came from (the only form `Location` can hold, since
+/// `Ident` itself carries no span).
+fn ident_location(ident: &Ident) -> Location {
+    Location::At(TokenTree::Token(Token::Ident(ident.clone())))
+}
+
+/// A cursor over a slice of token trees.
+///
+/// Ports rust-analyzer's `tt_iter` / `tt_cursor` design to this crate:
+/// instead of every parsing function hand-rolling its own
+/// `Peekable<Iter<TokenTree>>` walking and duplicating the same
+/// expect-this-or-bail logic, `TtCursor` centralizes it. Every `expect_*`
+/// method either consumes the token(s) it expects or returns the located
+/// [`ParseError`] pointing at the mismatch.
+struct TtCursor<'a> {
+    tts: &'a [TokenTree],
+    pos: usize,
+}
+
+impl<'a> TtCursor<'a> {
+    fn new(tts: &'a [TokenTree]) -> Self {
+        TtCursor { tts, pos: 0 }
+    }
+
+    /// Returns the next token tree without consuming it.
+    fn peek(&self) -> Option<&'a TokenTree> {
+        self.tts.get(self.pos)
+    }
+
+    /// Consumes and returns the next token tree, if any.
+    fn bump(&mut self) -> Option<&'a TokenTree> {
+        let tt = self.tts.get(self.pos);
+        if tt.is_some() {
+            self.pos += 1;
+        }
+        tt
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.tts.len()
+    }
+
+    /// Consumes an `Ident`, or errors pointing at whatever was found
+    /// instead.
+    fn expect_ident(&mut self) -> PResult<&'a Ident> {
+        match self.bump() {
+            Some(&TokenTree::Token(Token::Ident(ref id))) => Ok(id),
+            tt => Err(ParseError::Expected {
+                at: tt_location(tt),
+                what: "an identifier".into(),
+            }),
+        }
+    }
+
+    /// Consumes `tok`, or errors pointing at whatever was found instead.
+    fn expect_punct(&mut self, tok: Token) -> PResult<()> {
+        match self.bump() {
+            Some(tt) if tt == &TokenTree::Token(tok.clone()) => Ok(()),
+            tt => Err(ParseError::Expected {
+                at: tt_location(tt),
+                what: format!("`{:?}`", tok),
+            }),
+        }
+    }
+
+    /// Consumes `tok` if it's next, returning whether it did.
+    fn eat_punct(&mut self, tok: Token) -> bool {
+        match self.peek() {
+            Some(tt) if tt == &TokenTree::Token(tok) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Consumes a `delimiter`-delimited group and returns a cursor over its
+    /// contents.
+    fn expect_delimited(&mut self, delimiter: DelimToken) -> PResult<TtCursor<'a>> {
+        match self.bump() {
+            Some(TokenTree::Delimited(block)) => {
+                if block.delim != delimiter {
+                    return Err(ParseError::UnexpectedToken {
+                        at: Location::At(self.tts[self.pos - 1].clone()),
+                        found: format!("{:?}-delimited group", block.delim),
+                    });
+                }
+
+                Ok(TtCursor::new(&block.tts))
+            }
+            tt => Err(ParseError::Expected {
+                at: tt_location(tt),
+                what: format!("a {:?}-delimited group", delimiter),
+            }),
+        }
+    }
+}
+
+/// Advances `tts` up to (but not including) the next top-level `,`, or to
+/// the end of input if there isn't one.
+///
+/// A `{...}` / `[...]` / `(...)` group is represented as a single, opaque
+/// [`TokenTree::Delimited`] here, so scanning for a bare `Comma` at this
+/// level can never mistake a comma nested inside e.g. `tasks { a: {...},
+/// b: {...} }` for the top-level separator between fields — the nested
+/// commas simply aren't part of this slice, so no explicit depth counter
+/// is needed to skip over them.
+fn skip_to_comma(tts: &mut TtCursor) {
+    while let Some(tt) = tts.peek() {
+        if tt == &TokenTree::Token(Token::Comma) {
+            break;
+        }
+
+        tts.bump();
+    }
+}
+
+/// Parses an `app!` invocation, recovering from errors in individual
+/// top-level fields instead of aborting at the first one.
+///
+/// When a field's value fails to parse, the error is recorded and the
+/// cursor is resynchronized at the next top-level field (by skipping to
+/// the next `,`), so that a user with several typos sees every one of them
+/// in a single pass instead of fixing and recompiling one at a time.
+pub fn app(input: &str) -> ::std::result::Result<App, Vec<ParseError>> {
+    let tts = syn::parse_token_trees(input).map_err(|e| {
+        vec![
+            ParseError::Expected {
+                at: Location::EndOfInput,
+                what: format!("valid Rust tokens ({})", e),
+            },
+        ]
+    })?;
+    let mut cursor = TtCursor::new(&tts);
 
     let mut device = None;
     let mut idle = None;
     let mut init = None;
     let mut resources = None;
     let mut tasks = None;
-
-    fields(&tts, |key, tts| {
-        match key.as_ref() {
-            "device" => {
-                ensure!(device.is_none(), "duplicated `device` field");
-
-                device =
-                    Some(::parse::path(tts).chain_err(|| "parsing `device`")?);
+    let mut errors = vec![];
+
+    while !cursor.eof() {
+        let key = match cursor.expect_ident() {
+            Ok(key) => key,
+            Err(e) => {
+                errors.push(e);
+                skip_to_comma(&mut cursor);
+                cursor.eat_punct(Token::Comma);
+                continue;
             }
-            "idle" => {
-                ensure!(idle.is_none(), "duplicated `idle` field");
+        };
 
-                idle = Some(::parse::idle(tts).chain_err(|| "parsing `idle`")?);
-            }
-            "init" => {
-                ensure!(init.is_none(), "duplicated `init` field");
+        if let Err(e) = cursor.expect_punct(Token::Colon) {
+            errors.push(e);
+            skip_to_comma(&mut cursor);
+            cursor.eat_punct(Token::Comma);
+            continue;
+        }
 
-                init = Some(::parse::init(tts).chain_err(|| "parsing `init`")?);
-            }
-            "resources" => {
-                ensure!(resources.is_none(), "duplicated `resources` field");
+        let result = match key.as_ref() {
+            "device" => if device.is_some() {
+                Err(ParseError::DuplicateField {
+                    at: ident_location(key),
+                    name: "device".into(),
+                })
+            } else {
+                ::parse::path(&mut cursor).map(|value| device = Some(value))
+            },
+            "idle" => if idle.is_some() {
+                Err(ParseError::DuplicateField {
+                    at: ident_location(key),
+                    name: "idle".into(),
+                })
+            } else {
+                ::parse::idle(&mut cursor).map(|value| idle = Some(value))
+            },
+            "init" => if init.is_some() {
+                Err(ParseError::DuplicateField {
+                    at: ident_location(key),
+                    name: "init".into(),
+                })
+            } else {
+                ::parse::init(&mut cursor).map(|value| init = Some(value))
+            },
+            "resources" => if resources.is_some() {
+                Err(ParseError::DuplicateField {
+                    at: ident_location(key),
+                    name: "resources".into(),
+                })
+            } else {
+                ::parse::statics(&mut cursor).map(|value| resources = Some(value))
+            },
+            "tasks" => if tasks.is_some() {
+                Err(ParseError::DuplicateField {
+                    at: ident_location(key),
+                    name: "tasks".into(),
+                })
+            } else {
+                ::parse::tasks(&mut cursor).map(|value| tasks = Some(value))
+            },
+            _ => Err(ParseError::UnexpectedToken {
+                at: ident_location(key),
+                found: format!("unknown field `{}`", key),
+            }),
+        };
 
-                resources = Some(
-                    ::parse::statics(tts).chain_err(|| "parsing `resources`")?,
-                );
-            }
-            "tasks" => {
-                ensure!(tasks.is_none(), "duplicated `tasks` field");
+        if let Err(e) = result {
+            errors.push(e);
+            skip_to_comma(&mut cursor);
+        }
 
-                tasks =
-                    Some(::parse::tasks(tts).chain_err(|| "parsing `tasks`")?);
+        if !cursor.eof() {
+            if let Err(e) = cursor.expect_punct(Token::Comma) {
+                errors.push(e);
+                skip_to_comma(&mut cursor);
+                cursor.eat_punct(Token::Comma);
             }
-            _ => bail!("unknown field: `{}`", key),
         }
+    }
 
-        Ok(())
-    })?;
+    if device.is_none() {
+        errors.push(ParseError::MissingField {
+            name: "device".into(),
+        });
+    }
+    if idle.is_none() {
+        errors.push(ParseError::MissingField {
+            name: "idle".into(),
+        });
+    }
+    if init.is_none() {
+        errors.push(ParseError::MissingField {
+            name: "init".into(),
+        });
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
     Ok(App {
-        device: device.ok_or("`device` field is missing")?,
-        idle: idle.ok_or("`idle` field is missing")?,
-        init: init.ok_or("`init` field is missing")?,
+        device: device.unwrap(),
+        idle: idle.unwrap(),
+        init: init.unwrap(),
         resources: resources.unwrap_or(HashMap::new()),
         tasks: tasks.unwrap_or(HashMap::new()),
     })
 }
 
-fn bool(tt: Option<&TokenTree>) -> Result<bool> {
+fn bool(tt: Option<&TokenTree>) -> PResult<bool> {
     if let Some(&TokenTree::Token(Token::Literal(Lit::Bool(bool)))) = tt {
         Ok(bool)
     } else {
-        bail!("expected boolean, found {:?}", tt);
+        Err(ParseError::Expected {
+            at: tt_location(tt),
+            what: "a boolean literal".into(),
+        })
     }
 }
 
-fn delimited<R, F>(
-    tts: &mut Peekable<Iter<TokenTree>>,
-    delimiter: DelimToken,
-    f: F,
-) -> Result<R>
+// `$($key:ident: $($value:tt)*),*[,]`
+fn fields<F>(tts: &mut TtCursor, mut f: F) -> PResult<()>
 where
-    F: FnOnce(&[TokenTree]) -> Result<R>,
+    F: FnMut(&Ident, &mut TtCursor) -> PResult<()>,
 {
-    let tt = tts.next();
-    if let Some(&TokenTree::Delimited(ref block)) = tt {
-        ensure!(
-            block.delim == delimiter,
-            "expected {:?}, found {:?}",
-            delimiter,
-            block.delim
-        );
+    while !tts.eof() {
+        let ident = tts.expect_ident()?;
+        tts.expect_punct(Token::Colon)?;
 
-        f(&block.tts)
-    } else {
-        bail!("expected a Delimited sequence, found {:?}", tt);
+        f(ident, tts)?;
+
+        if !tts.eof() {
+            tts.expect_punct(Token::Comma)?;
+        }
     }
+
+    Ok(())
 }
 
-// `$($key:ident: $($value:tt)*),*[,]`
-fn fields<F>(tts: &[TokenTree], mut f: F) -> Result<()>
+/// Parses a `sep`-separated sequence of `T`s until `tts` runs out, via
+/// `element`.
+///
+/// Ports rust-analyzer's `Op`/`Separator`/`RepeatKind` model: a single
+/// engine for "an optional trailing `sep`, an empty list is fine, a
+/// doubled `sep` is an error" replaces the ad-hoc, mutually inconsistent
+/// list-walking that `idents`, `statics` and `tasks` used to each
+/// reimplement (one on `Comma`, one on `Semi`, neither reporting anything
+/// useful on e.g. `[A,,B]`).
+fn parse_separated<T, F>(tts: &mut TtCursor, sep: Token, mut element: F) -> PResult<Vec<T>>
 where
-    F: FnMut(&Ident, &mut Peekable<Iter<TokenTree>>) -> Result<()>,
+    F: FnMut(&mut TtCursor) -> PResult<T>,
 {
-    let mut tts = tts.iter().peekable();
+    let mut items = vec![];
 
-    while let Some(tt) = tts.next() {
-        let ident = if let TokenTree::Token(Token::Ident(ref id)) = *tt {
-            id
-        } else {
-            bail!("expected Ident, found {:?}", tt);
-        };
+    while !tts.eof() {
+        items.push(element(tts)?);
 
-        let tt = tts.next();
-        if let Some(&TokenTree::Token(Token::Colon)) = tt {
-        } else {
-            bail!("expected Colon, found {:?}", tt);
+        if !tts.eat_punct(sep.clone()) {
+            break;
         }
 
-        f(ident, &mut tts)?;
-
-        let tt = tts.next();
-        match tt {
-            None | Some(&TokenTree::Token(Token::Comma)) => {}
-            _ => bail!("expected Comma, found {:?}", tt),
+        if let Some(tt) = tts.peek() {
+            if tt == &TokenTree::Token(sep.clone()) {
+                return Err(ParseError::UnexpectedToken {
+                    at: Location::At(tt.clone()),
+                    found: format!("a second `{:?}`", sep),
+                });
+            }
         }
     }
 
-    Ok(())
+    if !tts.eof() {
+        return Err(ParseError::Expected {
+            at: tt_location(tts.peek()),
+            what: format!("`{:?}`", sep),
+        });
+    }
+
+    Ok(items)
 }
 
-fn idents(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Idents> {
-    ::parse::delimited(tts, DelimToken::Bracket, |tts| {
-        let mut idents = HashSet::new();
-
-        let mut tts = tts.iter().peekable();
-        while let Some(tt) = tts.next() {
-            if let &TokenTree::Token(Token::Ident(ref ident)) = tt {
-                ensure!(
-                    !idents.contains(ident),
-                    "ident {} listed more than once"
-                );
-
-                idents.insert(ident.clone());
-
-                if let Some(tt) = tts.next() {
-                    ensure!(
-                        tt == &TokenTree::Token(Token::Comma),
-                        "expected Comma, found {:?}",
-                        tt
-                    );
-
-                    if tts.peek().is_none() {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            } else {
-                bail!("expected Ident, found {:?}", tt);
-            }
+fn idents(tts: &mut TtCursor) -> PResult<Idents> {
+    let mut tts = tts.expect_delimited(DelimToken::Bracket)?;
+
+    let mut idents = HashSet::new();
+    for ident in parse_separated(&mut tts, Token::Comma, |tts| {
+        tts.expect_ident().cloned()
+    })? {
+        if idents.contains(&ident) {
+            return Err(ParseError::DuplicateItem {
+                at: ident_location(&ident),
+                kind: "ident",
+                name: ident.to_string(),
+            });
         }
 
-        Ok(idents)
-    })
+        idents.insert(ident);
+    }
+
+    Ok(idents)
 }
 
-fn idle(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Idle> {
-    ::parse::delimited(tts, DelimToken::Brace, |tts| {
-        let mut locals = None;
-        let mut path = None;
-        let mut resources = None;
+fn idle(tts: &mut TtCursor) -> PResult<Idle> {
+    let mut tts = tts.expect_delimited(DelimToken::Brace)?;
 
-        ::parse::fields(tts, |key, tts| {
-            match key.as_ref() {
-                "path" => {
-                    ensure!(path.is_none(), "duplicated `path` field");
+    let mut locals = None;
+    let mut path = None;
+    let mut resources = None;
 
-                    path = Some(::parse::path(tts)?);
+    fields(&mut tts, |key, tts| {
+        match key.as_ref() {
+            "path" => {
+                if path.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "path".into(),
+                    });
                 }
-                "locals" => {
-                    ensure!(locals.is_none(), "duplicated `locals` field");
 
-                    locals = Some(
-                        ::parse::statics(tts).chain_err(|| "parsing `locals`")?,
-                    );
+                path = Some(::parse::path(tts)?);
+            }
+            "locals" => {
+                if locals.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "locals".into(),
+                    });
                 }
-                "resources" => {
-                    ensure!(
-                        resources.is_none(),
-                        "duplicated `resources` field"
-                    );
-
-                    resources = Some(::parse::idents(tts)
-                        .chain_err(|| "parsing `resources`")?);
+
+                locals = Some(::parse::statics(tts)?);
+            }
+            "resources" => {
+                if resources.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "resources".into(),
+                    });
                 }
-                _ => bail!("unknown field: `{}`", key),
+
+                resources = Some(::parse::idents(tts)?);
+            }
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    at: ident_location(key),
+                    found: format!("unknown field `{}`", key),
+                })
             }
+        }
 
-            Ok(())
-        })?;
+        Ok(())
+    })?;
 
-        Ok(Idle {
-            locals: locals.unwrap_or(HashMap::new()),
-            path: path.ok_or("`path` field missing")?,
-            resources: resources.unwrap_or(HashSet::new()),
-        })
+    Ok(Idle {
+        locals: locals.unwrap_or(HashMap::new()),
+        path: path.ok_or_else(|| ParseError::MissingField {
+            name: "path".into(),
+        })?,
+        resources: resources.unwrap_or(HashSet::new()),
     })
 }
 
-fn init(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Init> {
-    ::parse::delimited(tts, DelimToken::Brace, |tts| {
-        let mut path = None;
+fn init(tts: &mut TtCursor) -> PResult<Init> {
+    let mut tts = tts.expect_delimited(DelimToken::Brace)?;
 
-        ::parse::fields(tts, |key, tts| {
-            match key.as_ref() {
-                "path" => {
-                    ensure!(path.is_none(), "duplicated `path` field");
+    let mut path = None;
 
-                    path = Some(::parse::path(tts)?);
+    fields(&mut tts, |key, tts| {
+        match key.as_ref() {
+            "path" => {
+                if path.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "path".into(),
+                    });
                 }
-                _ => bail!("unknown field: `{}`", key),
+
+                path = Some(::parse::path(tts)?);
             }
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    at: ident_location(key),
+                    found: format!("unknown field `{}`", key),
+                })
+            }
+        }
 
-            Ok(())
-        })?;
+        Ok(())
+    })?;
 
-        Ok(Init {
-            path: path.ok_or("`path` field missing")?,
-        })
+    Ok(Init {
+        path: path.ok_or_else(|| ParseError::MissingField {
+            name: "path".into(),
+        })?,
     })
 }
 
-/// `$ty:ty = $expr:expr`
-fn static_(tts: &mut Iter<TokenTree>) -> Result<Static> {
-    let mut fragments = vec![];
+/// Consumes the shortest run of tokens up to a top-level `sep` that
+/// `parse`s as a complete `T`, stopping with `sep` itself still unconsumed.
+///
+/// A naive "stop at the first `sep`" scan breaks as soon as the fragment
+/// legitimately contains a bare `sep`, e.g. an associated type binding like
+/// `Iterator<Item = u8>` contains an `=` that isn't the field's own
+/// separator. So
+/// whenever the candidate fragment fails to parse, `sep` is folded into
+/// the fragment and the scan resumes looking for the next occurrence,
+/// mirroring how `mbe`'s matcher invokes the real grammar entry point for
+/// a metavariable's `FragmentKind` instead of cutting on a fixed token.
+/// `sep` is left in place (rather than consumed here) because some callers
+/// reuse it as the separator between repeated fragments, e.g. the `;` that
+/// ends a `static`'s initializer is also the separator between `statics`
+/// entries. Running out of input is also accepted as the end of the last
+/// fragment in a list, so that the final entry doesn't need a trailing
+/// `sep` just for `fragment` to notice it's done.
+fn fragment<T, F>(tts: &mut TtCursor, sep: Token, what: &str, parse: F) -> PResult<T>
+where
+    F: Fn(&str) -> ::std::result::Result<T, String>,
+{
+    let mut fragment_tts: Vec<TokenTree> = vec![];
+
     loop {
-        if let Some(tt) = tts.next() {
-            if tt == &TokenTree::Token(Token::Eq) {
-                break;
-            } else {
-                fragments.push(tt);
-            }
-        } else {
-            bail!("expected Equal, found end of macro");
-        }
-    }
+        match tts.peek() {
+            Some(tt) if tt == &TokenTree::Token(sep.clone()) => {
+                if fragment_tts.is_empty() {
+                    return Err(ParseError::Expected {
+                        at: Location::At(tt.clone()),
+                        what: what.into(),
+                    });
+                }
 
-    ensure!(!fragments.is_empty(), "type is missing");
-    let ty = quote!(#(#fragments)*);
+                let fragment_tts_ref = &fragment_tts;
+                let candidate = quote!(#(#fragment_tts_ref)*).to_string();
+                if let Ok(value) = parse(&candidate) {
+                    return Ok(value);
+                }
 
-    let mut fragments = vec![];
-    loop {
-        if let Some(tt) = tts.next() {
-            if tt == &TokenTree::Token(Token::Semi) {
-                break;
-            } else {
-                fragments.push(tt);
+                fragment_tts.push(tts.bump().unwrap().clone());
+            }
+            Some(tt) => {
+                fragment_tts.push(tt.clone());
+                tts.bump();
+            }
+            None => {
+                if !fragment_tts.is_empty() {
+                    let fragment_tts_ref = &fragment_tts;
+                    let candidate = quote!(#(#fragment_tts_ref)*).to_string();
+                    if let Ok(value) = parse(&candidate) {
+                        return Ok(value);
+                    }
+                }
+
+                return Err(ParseError::Expected {
+                    at: Location::EndOfInput,
+                    what: what.into(),
+                });
             }
-        } else {
-            bail!("expected Semicolon, found end of macro");
         }
     }
+}
 
-    ensure!(!fragments.is_empty(), "initial value is missing");
-    let expr = quote!(#(#fragments)*);
+/// `$ty:ty = $expr:expr`
+fn static_(tts: &mut TtCursor) -> PResult<Static> {
+    let ty = fragment(tts, Token::Eq, "a type", |s| {
+        syn::parse_type(s).map_err(|e| e.to_string())
+    })?;
+    tts.expect_punct(Token::Eq)?;
+
+    let expr = fragment(tts, Token::Semi, "an initial value", |s| {
+        syn::parse_expr(s).map_err(|e| e.to_string())
+    })?;
 
     Ok(Static { expr, ty })
 }
 
 /// $($ident:ident: $ty:ty = $expr:expr);*
-fn statics(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Statics> {
-    ::parse::delimited(tts, DelimToken::Brace, |tts| {
-        let mut statics = HashMap::new();
-
-        let mut tts = tts.iter();
-        while let Some(tt) = tts.next() {
-            let ident = if let &TokenTree::Token(Token::Ident(ref id)) = tt {
-                id
-            } else {
-                bail!("expected Ident, found {:?}", tt);
-            };
+fn statics(tts: &mut TtCursor) -> PResult<Statics> {
+    let mut tts = tts.expect_delimited(DelimToken::Brace)?;
 
-            ensure!(
-                !statics.contains_key(ident),
-                "resource {} listed more than once",
-                ident
-            );
+    let mut statics = HashMap::new();
+    let entries = parse_separated(&mut tts, Token::Semi, |tts| {
+        let ident = tts.expect_ident()?.clone();
+        tts.expect_punct(Token::Colon)?;
+        let static_ = static_(tts)?;
 
-            let tt = tts.next();
-            if let Some(&TokenTree::Token(Token::Colon)) = tt {
-            } else {
-                bail!("expected Colon, found {:?}", tt);
-            }
+        Ok((ident, static_))
+    })?;
 
-            statics.insert(
-                ident.clone(),
-                ::parse::static_(&mut tts)
-                    .chain_err(|| format!("parsing `{}`", ident))?,
-            );
+    for (ident, static_) in entries {
+        if statics.contains_key(&ident) {
+            return Err(ParseError::DuplicateItem {
+                at: ident_location(&ident),
+                kind: "resource",
+                name: ident.to_string(),
+            });
         }
 
-        Ok(statics)
-    })
+        statics.insert(ident, static_);
+    }
+
+    Ok(statics)
 }
 
-fn path(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Tokens> {
+fn path(tts: &mut TtCursor) -> PResult<Tokens> {
     let mut fragments = vec![];
 
     loop {
-        if let Some(tt) = tts.peek() {
-            if tt == &&TokenTree::Token(Token::Comma) {
-                break;
-            } else {
-                fragments.push(tt.clone());
-            }
-        } else {
-            bail!("expected Comma, found end of macro")
+        match tts.peek() {
+            Some(&TokenTree::Token(Token::Comma)) | None => break,
+            Some(tt) => fragments.push(tt.clone()),
         }
 
-        tts.next();
+        tts.bump();
     }
 
     Ok(quote!(#(#fragments)*))
 }
 
-fn task(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Task> {
-    ::parse::delimited(tts, DelimToken::Brace, |tts| {
-        let mut enabled = None;
-        let mut priority = None;
-        let mut resources = None;
+fn task(tts: &mut TtCursor) -> PResult<Task> {
+    let mut tts = tts.expect_delimited(DelimToken::Brace)?;
 
-        ::parse::fields(tts, |key, tts| {
-            match key.as_ref() {
-                "enabled" => {
-                    ensure!(enabled.is_none(), "duplicated `enabled` field");
+    let mut enabled = None;
+    let mut priority = None;
+    let mut resources = None;
 
-                    enabled = Some(::parse::bool(tts.next())
-                        .chain_err(|| "parsing `enabled`")?);
+    fields(&mut tts, |key, tts| {
+        match key.as_ref() {
+            "enabled" => {
+                if enabled.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "enabled".into(),
+                    });
                 }
-                "priority" => {
-                    ensure!(priority.is_none(), "duplicated `priority` field");
 
-                    priority = Some(::parse::u8(tts.next())
-                        .chain_err(|| "parsing `priority`")?);
+                enabled = Some(::parse::bool(tts.bump())?);
+            }
+            "priority" => {
+                if priority.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "priority".into(),
+                    });
                 }
-                "resources" => {
-                    ensure!(
-                        resources.is_none(),
-                        "duplicated `resources` field"
-                    );
-
-                    resources = Some(::parse::idents(tts)
-                        .chain_err(|| "parsing `resources`")?);
+
+                priority = Some(::parse::u8(tts.bump())?);
+            }
+            "resources" => {
+                if resources.is_some() {
+                    return Err(ParseError::DuplicateField {
+                        at: ident_location(key),
+                        name: "resources".into(),
+                    });
                 }
-                _ => bail!("unknown field: `{}`", key),
+
+                resources = Some(::parse::idents(tts)?);
+            }
+            _ => {
+                return Err(ParseError::UnexpectedToken {
+                    at: ident_location(key),
+                    found: format!("unknown field `{}`", key),
+                })
             }
+        }
 
-            Ok(())
-        })?;
+        Ok(())
+    })?;
 
-        Ok(Task {
-            enabled,
-            priority,
-            resources: resources.unwrap_or(HashSet::new()),
-        })
+    Ok(Task {
+        enabled,
+        priority,
+        resources: resources.unwrap_or(HashSet::new()),
     })
 }
 
-fn tasks(tts: &mut Peekable<Iter<TokenTree>>) -> Result<Tasks> {
-    ::parse::delimited(tts, DelimToken::Brace, |tts| {
-        let mut tasks = HashMap::new();
+fn tasks(tts: &mut TtCursor) -> PResult<Tasks> {
+    let mut tts = tts.expect_delimited(DelimToken::Brace)?;
 
-        ::parse::fields(tts, |key, tts| {
-            ensure!(
-                !tasks.contains_key(key),
-                "task {} listed more than once",
-                key
-            );
+    let mut tasks = HashMap::new();
+    let entries = parse_separated(&mut tts, Token::Comma, |tts| {
+        let ident = tts.expect_ident()?.clone();
+        tts.expect_punct(Token::Colon)?;
+        let task = task(tts)?;
 
-            tasks.insert(
-                key.clone(),
-                ::parse::task(tts)
-                    .chain_err(|| format!("parsing task `{}`", key))?,
-            );
+        Ok((ident, task))
+    })?;
 
-            Ok(())
-        })?;
+    for (ident, task) in entries {
+        if tasks.contains_key(&ident) {
+            return Err(ParseError::DuplicateItem {
+                at: ident_location(&ident),
+                kind: "task",
+                name: ident.to_string(),
+            });
+        }
 
-        Ok(tasks)
-    })
+        tasks.insert(ident, task);
+    }
+
+    Ok(tasks)
 }
 
-fn u8(tt: Option<&TokenTree>) -> Result<u8> {
+fn u8(tt: Option<&TokenTree>) -> PResult<u8> {
     if let Some(
         &TokenTree::Token(
             Token::Literal(Lit::Int(priority, IntTy::Unsuffixed)),
         ),
     ) = tt
     {
-        ensure!(priority < 256, "{} is out of the `u8` range", priority);
+        if priority > 255 {
+            return Err(ParseError::Expected {
+                at: tt_location(tt),
+                what: format!("a value in the `u8` range (found {})", priority),
+            });
+        }
 
         Ok(priority as u8)
     } else {
-        bail!("expected integer, found {:?}", tt);
+        Err(ParseError::Expected {
+            at: tt_location(tt),
+            what: "an integer".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::Ident;
+
+    use ::app;
+
+    #[test]
+    fn array_repeat_expr_is_not_truncated() {
+        let app = app(
+            r#"
+                device: stm32f103xx,
+                idle: { path: idle::idle },
+                init: { path: init::init },
+                resources: {
+                    BUF: [u8; 16] = [0u8; 16];
+                },
+        "#,
+        ).unwrap();
+
+        let buf = &app.resources[&Ident::from("BUF")];
+        let ty = &buf.ty;
+        let expr = &buf.expr;
+        assert_eq!(quote!(#ty).to_string(), "[ u8 ; 16 ]");
+        assert_eq!(quote!(#expr).to_string(), "[ 0u8 ; 16 ]");
+    }
+
+    #[test]
+    fn last_static_without_trailing_semicolon_parses() {
+        let app = app(
+            r#"
+                device: stm32f103xx,
+                idle: { path: idle::idle },
+                init: { path: init::init },
+                resources: {
+                    A: u8 = 0
+                },
+        "#,
+        ).unwrap();
+
+        assert_eq!(app.resources.len(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn doubled_separator_is_an_error() {
+        let errors = app(
+            r#"
+                device: stm32f103xx,
+                idle: {
+                    path: idle::idle,
+                    resources: [A, , B],
+                },
+                init: { path: init::init },
+        "#,
+        ).unwrap_err();
+
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.to_string().contains("a second")),
+            "expected a doubled-separator error, got {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn empty_lists_are_allowed() {
+        let app = app(
+            r#"
+                device: stm32f103xx,
+                idle: {
+                    path: idle::idle,
+                    resources: [],
+                },
+                init: { path: init::init },
+                resources: {},
+                tasks: {},
+        "#,
+        ).unwrap();
+
+        assert!(app.idle.resources.is_empty());
+        assert!(app.resources.is_empty());
+        assert!(app.tasks.is_empty());
+    }
+
+    #[test]
+    fn multiple_errors_are_recovered() {
+        let errors = app(
+            r#"
+                idle: { path: idle::idle },
+                init: { bogus: 0 },
+                tasks: { A: { priority: 999 } },
+        "#,
+        ).unwrap_err();
+
+        // `device` is missing *and* `init`'s `bogus` field *and* `tasks.A`'s
+        // out-of-range priority are all reported, instead of bailing out on
+        // the first problem encountered.
+        assert!(errors.len() >= 3, "expected >= 3 errors, got {:?}", errors);
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.to_string().contains("device"))
+        );
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.to_string().contains("bogus"))
+        );
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.to_string().contains("u8"))
+        );
+    }
+}